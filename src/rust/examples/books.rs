@@ -4,15 +4,25 @@ use std::{
 };
 
 use anyhow::Result;
-use byteorder::{LittleEndian, WriteBytesExt};
 use clap::Parser;
-use redis::{JsonCommands, Value};
+use redis::JsonCommands;
 use ru402::book::{Book, Recommendations};
+use ru402::cache::EmbeddingCache;
+use ru402::collab::{
+    create_cf_index, fuse_recommendations, get_collab_recommendation, store_latent_factors,
+    train_implicit_mf, Rating,
+};
+use ru402::queue::{flush_batch, truncate_description, EmbeddingQueue};
+use ru402::recommend::{
+    create_index, get_hybrid_recommendation, get_recommendation, get_recommendation_by_range,
+};
 use rust_bert::pipelines::sentence_embeddings::{
     SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType,
 };
 
-const INDEX_NAME: &str = "idx:books";
+const MODEL_TAG: &str = "AllMiniLmL6V2";
+// standard reciprocal-rank-fusion damping constant
+const RRF_K: f32 = 60.0;
 
 #[derive(Debug, Parser)]
 struct Cli {
@@ -28,6 +38,51 @@ struct Cli {
     /// Load the data (set to false to skip loading the data and just query the index)
     #[clap(short, long, action = clap::ArgAction::Set)]
     load: bool,
+    /// Use the hybrid recommender, constraining the KNN search with the seed book's own metadata
+    #[clap(long, action = clap::ArgAction::Set, default_value_t = false)]
+    hybrid: bool,
+    /// Only recommend books that share a genre with the seed book
+    #[clap(long, action = clap::ArgAction::Set, default_value_t = false)]
+    same_genre: bool,
+    /// Only recommend books with at least this `metrics.score`
+    #[clap(long)]
+    min_rating: Option<f32>,
+    /// Only recommend books that currently have an available copy in inventory
+    #[clap(long, action = clap::ArgAction::Set, default_value_t = false)]
+    available_only: bool,
+    /// Only recommend books published in or after this year
+    #[clap(long)]
+    min_year: Option<u16>,
+    /// Only recommend books published in or before this year
+    #[clap(long)]
+    max_year: Option<u16>,
+    /// Blend weight between vector similarity (1.0) and book rating (0.0) when re-ranking hybrid results
+    #[clap(long, default_value_t = 0.7)]
+    alpha: f32,
+    /// Skip the embedding cache and always re-encode every description
+    #[clap(long, action = clap::ArgAction::Set, default_value_t = false)]
+    no_cache: bool,
+    /// Maximum number of books to encode in a single embedding batch
+    #[clap(long, default_value_t = 32)]
+    batch_size: usize,
+    /// Maximum approximate token count to encode in a single embedding batch
+    #[clap(long, default_value_t = 2048)]
+    batch_tokens: usize,
+    /// Train the collaborative-filtering recommender from ../../data/ratings.json and index it
+    #[clap(long, action = clap::ArgAction::Set, default_value_t = false)]
+    train_collab: bool,
+    /// Number of latent factors to learn per user/book for the collaborative-filtering recommender
+    #[clap(long, default_value_t = 32)]
+    cf_factors: usize,
+    /// Number of training passes over the ratings dataset
+    #[clap(long, default_value_t = 20)]
+    cf_iterations: usize,
+    /// Comma-separated recommenders to blend via reciprocal-rank fusion, e.g. "content,collab"
+    #[clap(long)]
+    blend: Option<String>,
+    /// Comma-separated weights matching --blend, e.g. "0.6,0.4" (defaults to equal weights)
+    #[clap(long)]
+    weights: Option<String>,
 }
 
 fn main() -> Result<()> {
@@ -43,17 +98,39 @@ fn main() -> Result<()> {
 
     // read the books and store them as json documents, generating and adding in the embeddings for the description
     if args.load {
+        let cache = EmbeddingCache::new(MODEL_TAG);
+        let mut queue = EmbeddingQueue::new(args.batch_size, args.batch_tokens);
+
         for file in fs::read_dir("../../data/books")? {
             let file = file?;
             let file = File::open(file.path())?;
             let reader = BufReader::new(file);
             let mut book: Book = serde_json::from_reader(reader).unwrap();
-            let key = format!("book:{}", book.id);
 
-            let embedding = model.encode(&[&book.description])?;
-            book.embedding = Some(embedding[0].to_vec());
-            connection.json_set(key, "$", &book)?;
+            // truncate before hashing so the cache key matches the description `flush_batch`
+            // will actually encode and cache on a miss
+            truncate_description(&mut book);
+
+            let cached = if args.no_cache {
+                None
+            } else {
+                cache.get(&mut connection, &book.description)?
+            };
+
+            // a cache hit skips the batch entirely; only books that need re-encoding go through it
+            if let Some(embedding) = cached {
+                book.embedding = Some(embedding);
+                connection.json_set(format!("book:{}", book.id), "$", &book)?;
+                continue;
+            }
+
+            if let Some(batch) = queue.enqueue(book) {
+                flush_batch(&mut connection, &model, &cache, args.no_cache, batch)?;
+            }
         }
+
+        let remaining = queue.drain();
+        flush_batch(&mut connection, &model, &cache, args.no_cache, remaining)?;
     }
 
     // create the index
@@ -61,28 +138,105 @@ fn main() -> Result<()> {
         create_index(&mut connection)?;
     }
 
+    // learn per-book latent vectors from the ratings dataset and index them for collaborative
+    // filtering - "people who read this also read..." recommendations
+    if args.train_collab {
+        let file = File::open("../../data/ratings.json")?;
+        let reader = BufReader::new(file);
+        let ratings: Vec<Rating> = serde_json::from_reader(reader)?;
+
+        let factors = train_implicit_mf(&ratings, args.cf_factors, args.cf_iterations);
+        create_cf_index(&mut connection, args.cf_factors)?;
+        store_latent_factors(&mut connection, &factors)?;
+    }
+
     // run sample queries if no id was specified
     if args.id.is_empty() {
         println!("Recommendations for book:26415");
-        print_recommendations(get_recommendation(&mut connection, "book:26415")?);
+        print_recommendations(get_recommendation(&mut connection, "book:26415", 5)?);
         println!("Recommendations for book:9");
-        print_recommendations(get_recommendation(&mut connection, "book:9")?);
+        print_recommendations(get_recommendation(&mut connection, "book:9", 5)?);
 
         println!("Recommendations by range for book:26415");
-        print_recommendations(get_recommendation_by_range(&mut connection, "book:26415")?);
+        print_recommendations(get_recommendation_by_range(
+            &mut connection,
+            "book:26415",
+            3.0,
+            5,
+        )?);
         println!("Recommendations by range for book:9");
-        print_recommendations(get_recommendation_by_range(&mut connection, "book:9")?);
+        print_recommendations(get_recommendation_by_range(&mut connection, "book:9", 3.0, 5)?);
     } else {
         println!("Recommendations for {}", args.id);
-        print_recommendations(get_recommendation(&mut connection, &args.id)?);
+        if let Some(blend) = &args.blend {
+            print_recommendations(get_blended_recommendation(
+                &mut connection,
+                &args.id,
+                blend,
+                &args.weights,
+            )?);
+        } else if args.hybrid {
+            print_recommendations(get_hybrid_recommendation(
+                &mut connection,
+                &args.id,
+                args.same_genre,
+                args.min_rating,
+                args.available_only,
+                args.min_year,
+                args.max_year,
+                args.alpha,
+                5,
+            )?);
+        } else {
+            print_recommendations(get_recommendation(&mut connection, &args.id, 5)?);
+        }
 
         println!("Recommendations by range for {}", args.id);
-        print_recommendations(get_recommendation_by_range(&mut connection, &args.id)?);
+        print_recommendations(get_recommendation_by_range(
+            &mut connection,
+            &args.id,
+            3.0,
+            5,
+        )?);
     }
 
     Ok(())
 }
 
+// run each requested recommender and fuse the ranked lists via reciprocal-rank fusion
+fn get_blended_recommendation(
+    connection: &mut redis::Connection,
+    key: &str,
+    blend: &str,
+    weights: &Option<String>,
+) -> Result<Recommendations> {
+    let modes: Vec<&str> = blend.split(',').map(str::trim).collect();
+    let weights: Vec<f32> = match weights {
+        Some(weights) => weights
+            .split(',')
+            .map(|w| w.trim().parse::<f32>())
+            .collect::<std::result::Result<Vec<f32>, _>>()?,
+        None => vec![1.0; modes.len()],
+    };
+
+    anyhow::ensure!(
+        modes.len() == weights.len(),
+        "--blend and --weights must list the same number of entries"
+    );
+
+    let mut lists = Vec::with_capacity(modes.len());
+    for (mode, weight) in modes.iter().zip(weights) {
+        let recommendations = match *mode {
+            "content" => get_recommendation(connection, key, 5)?,
+            "collab" => get_collab_recommendation(connection, key, 5)?,
+            other => anyhow::bail!("Unknown blend mode '{}', expected 'content' or 'collab'", other),
+        };
+        lists.push((recommendations, weight));
+    }
+
+    Ok(fuse_recommendations(&lists, RRF_K, 5))
+}
+
 // print the recommendations
 fn print_recommendations(recommendations: Recommendations) {
     for r in &recommendations.recommendations {
@@ -92,135 +246,3 @@ fn print_recommendations(recommendations: Recommendations) {
         );
     }
 }
-
-// encode the embeddings as a byte array
-fn encode(fs: Vec<f32>) -> Vec<u8> {
-    let mut vec: Vec<u8> = Vec::new();
-    for f in fs {
-        vec.write_f32::<LittleEndian>(f).unwrap();
-    }
-    vec
-}
-
-// create the index if it doesn't exist
-fn create_index(connection: &mut redis::Connection) -> Result<()> {
-    let result: Result<Value, _> = redis::cmd("FT._LIST").query(connection);
-    if let Ok(Value::Bulk(ref values)) = result {
-        if values
-            .iter()
-            .any(|v| v == &Value::Status(String::from(INDEX_NAME)))
-        {
-            return Ok(());
-        }
-    }
-
-    let _ = redis::cmd("FT.CREATE")
-        .arg(INDEX_NAME)
-        .arg("ON")
-        .arg("JSON")
-        .arg("PREFIX")
-        .arg("1")
-        .arg("book:")
-        .arg("SCHEMA")
-        // author
-        .arg("$.author")
-        .arg("AS")
-        .arg("author")
-        .arg("TEXT")
-        // title
-        .arg("$.title")
-        .arg("AS")
-        .arg("title")
-        .arg("TEXT")
-        // description
-        .arg("$.description")
-        .arg("AS")
-        .arg("description")
-        .arg("TEXT")
-        // embedding
-        .arg("$.embedding")
-        .arg("AS")
-        .arg("embedding")
-        .arg("VECTOR")
-        // search parameters
-        .arg("HNSW")
-        .arg("6")
-        .arg("TYPE")
-        .arg("FLOAT32")
-        .arg("DIM")
-        .arg("384")
-        .arg("DISTANCE_METRIC")
-        .arg("COSINE")
-        .query(connection)?;
-    Ok(())
-}
-
-// get the recommendations for a book
-fn get_recommendation(connection: &mut redis::Connection, key: &str) -> Result<Recommendations> {
-    let book: Book = connection.json_get(key, "$")?;
-
-    if let Some(embedding) = book.embedding {
-        let encoded_embedding = encode(embedding);
-        let query = "*=>[KNN 5 @embedding $vec AS score]";
-        let recommendations: Recommendations = redis::cmd("FT.SEARCH")
-            .arg(INDEX_NAME)
-            .arg(query)
-            .arg("PARAMS")
-            .arg(2)
-            .arg("vec")
-            .arg(encoded_embedding)
-            .arg("RETURN")
-            .arg("2")
-            .arg("title")
-            .arg("score")
-            .arg("SORTBY")
-            .arg("score")
-            .arg("LIMIT")
-            .arg(0)
-            .arg(5)
-            .arg("DIALECT")
-            .arg("2")
-            .query(connection)?;
-
-        return Ok(recommendations);
-    }
-    anyhow::bail!("No embedding found for book {}", key);
-}
-
-// get the recommendations for a book by range
-fn get_recommendation_by_range(
-    connection: &mut redis::Connection,
-    key: &str,
-) -> Result<Recommendations> {
-    let book: Book = connection.json_get(key, "$")?;
-
-    if let Some(embedding) = book.embedding {
-        let encoded_embedding = encode(embedding);
-        let query = "@embedding:[VECTOR_RANGE $radius $vec]=>{$YIELD_DISTANCE_AS: score}";
-
-        let recommendations: Recommendations = redis::cmd("FT.SEARCH")
-            .arg(INDEX_NAME)
-            .arg(query)
-            .arg("PARAMS")
-            .arg(4)
-            .arg("radius")
-            .arg(3)
-            .arg("vec")
-            .arg(encoded_embedding)
-            .arg("RETURN")
-            .arg(2)
-            .arg("title")
-            .arg("score")
-            .arg("SORTBY")
-            .arg("score")
-            .arg("LIMIT")
-            .arg(0)
-            .arg(5)
-            .arg("DIALECT")
-            .arg("2")
-            .query(connection)?;
-
-        return Ok(recommendations);
-    }
-    anyhow::bail!("No embedding found for book {}", key);
-}
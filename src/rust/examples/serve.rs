@@ -0,0 +1,189 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use clap::Parser;
+use ru402::book::Recommendations;
+use ru402::recommend::{
+    get_hybrid_recommendation, get_recommendation, get_recommendation_by_range,
+    get_recommendation_by_vector,
+};
+use rust_bert::pipelines::sentence_embeddings::{
+    SentenceEmbeddingsBuilder, SentenceEmbeddingsModel, SentenceEmbeddingsModelType,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Parser)]
+struct Cli {
+    /// The redis url
+    #[clap(short, long, default_value = "redis://127.0.0.1:6379")]
+    redis_url: String,
+    /// The address to listen on
+    #[clap(short, long, default_value = "0.0.0.0:3000")]
+    listen: String,
+}
+
+struct AppState {
+    pool: r2d2::Pool<redis::Client>,
+    model: Mutex<SentenceEmbeddingsModel>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    Knn,
+    Range,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Filters {
+    same_genre: bool,
+    min_rating: Option<f32>,
+    available_only: bool,
+    min_year: Option<u16>,
+    max_year: Option<u16>,
+    alpha: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecommendRequest {
+    id: String,
+    #[serde(default = "default_k")]
+    k: u64,
+    mode: Option<Mode>,
+    #[serde(default = "default_radius")]
+    radius: f32,
+    #[serde(default)]
+    filters: Filters,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecommendByTextRequest {
+    text: String,
+    #[serde(default = "default_k")]
+    k: u64,
+}
+
+fn default_k() -> u64 {
+    5
+}
+
+fn default_radius() -> f32 {
+    3.0
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    let client = redis::Client::open(args.redis_url)?;
+    let pool = r2d2::Pool::builder().build(client)?;
+
+    let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
+        .create_model()?;
+
+    let state = Arc::new(AppState {
+        pool,
+        model: Mutex::new(model),
+    });
+
+    let app = Router::new()
+        .route("/recommend", post(recommend))
+        .route("/recommend/by-text", post(recommend_by_text))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(&args.listen).await?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn recommend(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<RecommendRequest>,
+) -> Result<Json<Recommendations>, AppError> {
+    let recommendations = tokio::task::spawn_blocking(move || -> Result<Recommendations> {
+        let mut connection = state.pool.get()?;
+        let hybrid = request.filters.same_genre
+            || request.filters.min_rating.is_some()
+            || request.filters.available_only
+            || request.filters.min_year.is_some()
+            || request.filters.max_year.is_some();
+
+        match (request.mode.unwrap_or(Mode::Knn), hybrid) {
+            (Mode::Knn, true) => get_hybrid_recommendation(
+                &mut connection,
+                &request.id,
+                request.filters.same_genre,
+                request.filters.min_rating,
+                request.filters.available_only,
+                request.filters.min_year,
+                request.filters.max_year,
+                request.filters.alpha.unwrap_or(0.7),
+                request.k,
+            ),
+            (Mode::Knn, false) => get_recommendation(&mut connection, &request.id, request.k),
+            (Mode::Range, _) => get_recommendation_by_range(
+                &mut connection,
+                &request.id,
+                request.radius,
+                request.k,
+            ),
+        }
+    })
+    .await??;
+
+    Ok(Json(recommendations))
+}
+
+async fn recommend_by_text(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<RecommendByTextRequest>,
+) -> Result<Json<Recommendations>, AppError> {
+    let recommendations = tokio::task::spawn_blocking(move || -> Result<Recommendations> {
+        let mut connection = state.pool.get()?;
+        let embedding = {
+            let model = state.model.lock().unwrap();
+            model.encode(&[&request.text])?.swap_remove(0)
+        };
+        get_recommendation_by_vector(&mut connection, embedding, request.k)
+    })
+    .await??;
+
+    Ok(Json(recommendations))
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+// wrap anyhow::Error so handlers can return it directly and axum turns it into a 500 response
+struct AppError(anyhow::Error);
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: self.0.to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+impl<E> From<E> for AppError
+where
+    E: Into<anyhow::Error>,
+{
+    fn from(err: E) -> Self {
+        AppError(err.into())
+    }
+}
@@ -82,6 +82,8 @@ pub struct Book {
     pub id: String,
     pub description: String,
     pub embedding: Option<Vec<f32>>,
+    #[serde(default)]
+    pub cf_embedding: Option<Vec<f32>>,
     pub editions: Vec<Edition>,
     pub genres: Vec<String>,
     pub inventory: Vec<Inventory>,
@@ -97,7 +99,12 @@ impl FromRedisValue for Book {
         let json: String = FromRedisValue::from_redis_value(v)?;
         let books: Vec<Book> = serde_json::from_str(&json)?;
 
-        Ok(books.first().unwrap().clone())
+        books.into_iter().next().ok_or_else(|| {
+            redis::RedisError::from((
+                redis::ErrorKind::TypeError,
+                "JSON.GET returned an empty array, expected a book",
+            ))
+        })
     }
 }
 
@@ -106,6 +113,7 @@ pub struct Recommendation {
     pub id: String,
     pub score: f32,
     pub title: String,
+    pub rating: Option<f32>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -116,18 +124,44 @@ pub struct Recommendations {
 
 impl FromRedisValue for Recommendations {
     fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
-        let result = v.as_sequence() .unwrap();
+        let result = v.as_sequence().ok_or_else(|| {
+            redis::RedisError::from((
+                redis::ErrorKind::TypeError,
+                "FT.SEARCH reply was not an array",
+            ))
+        })?;
 
         let mut iter = result.iter();
         let mut recommendations = Vec::new();
 
-        let count: u64 = redis::from_redis_value(iter.next().unwrap())?;
+        let count: u64 = match iter.next() {
+            Some(v) => redis::from_redis_value(v)?,
+            None => {
+                return Err(redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "FT.SEARCH reply was empty, expected a leading result count",
+                )))
+            }
+        };
 
-        while let (Some(id), Some(values)) = (iter.next(), iter.next()) {
+        while let Some(id) = iter.next() {
             let id: String = redis::from_redis_value(id)?;
-            let mut values = values.as_sequence().unwrap().iter();
+            let values = iter.next().ok_or_else(|| {
+                redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "FT.SEARCH reply had a dangling document id with no fields array",
+                ))
+            })?;
+            let values = values.as_sequence().ok_or_else(|| {
+                redis::RedisError::from((
+                    redis::ErrorKind::TypeError,
+                    "FT.SEARCH document fields were not an array",
+                ))
+            })?;
+            let mut values = values.iter();
             let mut title: String = String::new();
             let mut score: f32 = 0.0;
+            let mut rating: Option<f32> = None;
 
             while let (Some(k), Some(v)) = (values.next(), values.next()) {
                 let key: String = redis::from_redis_value(k)?;
@@ -138,10 +172,18 @@ impl FromRedisValue for Recommendations {
                     "score" => {
                         score = redis::from_redis_value(v)?;
                     }
+                    "rating" => {
+                        rating = redis::from_redis_value(v)?;
+                    }
                     _ => {}
                 }
             }
-            recommendations.push(Recommendation { id, title, score });
+            recommendations.push(Recommendation {
+                id,
+                title,
+                score,
+                rating,
+            });
         }
 
         Ok(Recommendations {
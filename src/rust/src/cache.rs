@@ -0,0 +1,102 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use redis::{Commands, RedisResult};
+use sha1::{Digest, Sha1};
+
+const CACHE_KEY: &str = "cache:embeddings";
+
+// a Redis-backed cache of description -> embedding lookups, scoped to a single embedding model;
+// entries cached under a different model tag are treated as misses and evicted, since switching
+// SentenceEmbeddingsModelType produces vectors in an incompatible space
+pub struct EmbeddingCache {
+    model_tag: String,
+}
+
+impl EmbeddingCache {
+    pub fn new(model_tag: impl Into<String>) -> Self {
+        Self {
+            model_tag: model_tag.into(),
+        }
+    }
+
+    fn hash(&self, description: &str) -> String {
+        let mut hasher = Sha1::new();
+        hasher.update(description.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    // returns None on a cache miss or when the cached entry belongs to a different model,
+    // evicting the stale entry in the latter case
+    pub fn get(
+        &self,
+        connection: &mut redis::Connection,
+        description: &str,
+    ) -> RedisResult<Option<Vec<f32>>> {
+        let field = self.hash(description);
+        let cached: Option<Vec<u8>> = connection.hget(CACHE_KEY, &field)?;
+        let Some(bytes) = cached else {
+            return Ok(None);
+        };
+
+        let (tag, embedding) = decode_entry(&bytes);
+        if tag != self.model_tag {
+            connection.hdel(CACHE_KEY, &field)?;
+            return Ok(None);
+        }
+        Ok(Some(embedding))
+    }
+
+    pub fn put(
+        &self,
+        connection: &mut redis::Connection,
+        description: &str,
+        embedding: &[f32],
+    ) -> RedisResult<()> {
+        let field = self.hash(description);
+        connection.hset(CACHE_KEY, field, encode_entry(&self.model_tag, embedding))
+    }
+}
+
+// entry layout: `<model_tag>\0<little-endian f32 embedding>`
+fn encode_entry(model_tag: &str, embedding: &[f32]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(model_tag.len() + 1 + embedding.len() * 4);
+    buf.extend_from_slice(model_tag.as_bytes());
+    buf.push(0);
+    for f in embedding {
+        buf.write_f32::<LittleEndian>(*f).unwrap();
+    }
+    buf
+}
+
+fn decode_entry(bytes: &[u8]) -> (String, Vec<f32>) {
+    let split = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let tag = String::from_utf8_lossy(&bytes[..split]).into_owned();
+
+    let mut cursor = std::io::Cursor::new(&bytes[(split + 1).min(bytes.len())..]);
+    let mut embedding = Vec::new();
+    while let Ok(f) = cursor.read_f32::<LittleEndian>() {
+        embedding.push(f);
+    }
+    (tag, embedding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_round_trips_through_encode_decode() {
+        let embedding = vec![0.1, -0.2, 3.0];
+        let bytes = encode_entry("AllMiniLmL6V2", &embedding);
+        let (tag, decoded) = decode_entry(&bytes);
+
+        assert_eq!(tag, "AllMiniLmL6V2");
+        assert_eq!(decoded, embedding);
+    }
+
+    #[test]
+    fn hash_is_stable_for_the_same_description() {
+        let cache = EmbeddingCache::new("AllMiniLmL6V2");
+        assert_eq!(cache.hash("a description"), cache.hash("a description"));
+        assert_ne!(cache.hash("a description"), cache.hash("another one"));
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rand::Rng;
+use redis::{JsonCommands, Value};
+use serde::Deserialize;
+
+use crate::book::{Book, Recommendation, Recommendations};
+use crate::recommend::encode;
+
+pub const CF_INDEX_NAME: &str = "idx:books_cf";
+
+const CONFIDENCE_ALPHA: f32 = 1.0;
+const LEARNING_RATE: f32 = 0.01;
+const REGULARIZATION: f32 = 0.02;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rating {
+    pub user_id: String,
+    pub book_id: String,
+    pub rating: f32,
+}
+
+pub struct LatentFactors {
+    pub book_vectors: HashMap<String, Vec<f32>>,
+}
+
+// fit user/book latent vectors by SGD against an implicit-feedback confidence of 1 + alpha *
+// rating; only the book-side vectors are returned, since those are what get indexed
+pub fn train_implicit_mf(ratings: &[Rating], factors: usize, iterations: usize) -> LatentFactors {
+    let mut rng = rand::thread_rng();
+
+    let mut user_vectors: HashMap<String, Vec<f32>> = HashMap::new();
+    let mut book_vectors: HashMap<String, Vec<f32>> = HashMap::new();
+
+    for rating in ratings {
+        user_vectors
+            .entry(rating.user_id.clone())
+            .or_insert_with(|| random_vector(&mut rng, factors));
+        book_vectors
+            .entry(rating.book_id.clone())
+            .or_insert_with(|| random_vector(&mut rng, factors));
+    }
+
+    for _ in 0..iterations {
+        for rating in ratings {
+            let confidence = 1.0 + CONFIDENCE_ALPHA * rating.rating;
+
+            let user_vector = user_vectors[&rating.user_id].clone();
+            let book_vector = book_vectors[&rating.book_id].clone();
+            let error = confidence - dot(&user_vector, &book_vector);
+
+            let user_vector_mut = user_vectors.get_mut(&rating.user_id).unwrap();
+            for (u, b) in user_vector_mut.iter_mut().zip(&book_vector) {
+                *u += LEARNING_RATE * (error * b - REGULARIZATION * *u);
+            }
+
+            let book_vector_mut = book_vectors.get_mut(&rating.book_id).unwrap();
+            for (b, u) in book_vector_mut.iter_mut().zip(&user_vector) {
+                *b += LEARNING_RATE * (error * u - REGULARIZATION * *b);
+            }
+        }
+    }
+
+    LatentFactors { book_vectors }
+}
+
+fn random_vector(rng: &mut impl Rng, factors: usize) -> Vec<f32> {
+    (0..factors).map(|_| rng.gen_range(-0.1..0.1)).collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// create the collaborative-filtering index if it doesn't exist
+pub fn create_cf_index(connection: &mut redis::Connection, factors: usize) -> Result<()> {
+    let result: Result<Value, _> = redis::cmd("FT._LIST").query(connection);
+    if let Ok(Value::Bulk(ref values)) = result {
+        if values
+            .iter()
+            .any(|v| v == &Value::Status(String::from(CF_INDEX_NAME)))
+        {
+            return Ok(());
+        }
+    }
+
+    redis::cmd("FT.CREATE")
+        .arg(CF_INDEX_NAME)
+        .arg("ON")
+        .arg("JSON")
+        .arg("PREFIX")
+        .arg("1")
+        .arg("book:")
+        .arg("SCHEMA")
+        .arg("$.cf_embedding")
+        .arg("AS")
+        .arg("cf_embedding")
+        .arg("VECTOR")
+        .arg("HNSW")
+        .arg("6")
+        .arg("TYPE")
+        .arg("FLOAT32")
+        .arg("DIM")
+        .arg(factors)
+        .arg("DISTANCE_METRIC")
+        .arg("COSINE")
+        .query::<()>(connection)?;
+    Ok(())
+}
+
+// store the learned latent vectors on each book's own json document
+pub fn store_latent_factors(
+    connection: &mut redis::Connection,
+    factors: &LatentFactors,
+) -> Result<()> {
+    for (book_id, vector) in &factors.book_vectors {
+        let key = format!("book:{}", book_id);
+        connection.json_set(key, "$.cf_embedding", vector)?;
+    }
+    Ok(())
+}
+
+// get "people who read this also read" recommendations from the collaborative-filtering space
+pub fn get_collab_recommendation(
+    connection: &mut redis::Connection,
+    key: &str,
+    k: u64,
+) -> Result<Recommendations> {
+    let book: Book = connection.json_get(key, "$")?;
+
+    if let Some(embedding) = book.cf_embedding {
+        let encoded_embedding = encode(embedding);
+        let query = format!("*=>[KNN {} @cf_embedding $vec AS score]", k);
+
+        let recommendations: Recommendations = redis::cmd("FT.SEARCH")
+            .arg(CF_INDEX_NAME)
+            .arg(query)
+            .arg("PARAMS")
+            .arg(2)
+            .arg("vec")
+            .arg(encoded_embedding)
+            .arg("RETURN")
+            .arg("2")
+            .arg("title")
+            .arg("score")
+            .arg("SORTBY")
+            .arg("score")
+            .arg("LIMIT")
+            .arg(0)
+            .arg(k)
+            .arg("DIALECT")
+            .arg("2")
+            .query(connection)?;
+
+        return Ok(recommendations);
+    }
+    anyhow::bail!(
+        "No collaborative-filtering embedding found for book {}",
+        key
+    );
+}
+
+// fuse ranked recommendation lists via weighted reciprocal-rank fusion:
+// score(item) = sum weight_i / (k + rank_i + 1)
+pub fn fuse_recommendations(
+    lists: &[(Recommendations, f32)],
+    k: f32,
+    limit: usize,
+) -> Recommendations {
+    let mut fused: HashMap<String, Recommendation> = HashMap::new();
+
+    for (recommendations, weight) in lists {
+        for (rank, recommendation) in recommendations.recommendations.iter().enumerate() {
+            let contribution = weight / (k + rank as f32 + 1.0);
+            fused
+                .entry(recommendation.id.clone())
+                .and_modify(|existing| existing.score += contribution)
+                .or_insert_with(|| Recommendation {
+                    id: recommendation.id.clone(),
+                    title: recommendation.title.clone(),
+                    rating: recommendation.rating,
+                    score: contribution,
+                });
+        }
+    }
+
+    let mut fused: Vec<Recommendation> = fused.into_values().collect();
+    fused.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    fused.truncate(limit);
+
+    Recommendations {
+        count: fused.len() as u64,
+        recommendations: fused,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recommendation(id: &str, score: f32) -> Recommendation {
+        Recommendation {
+            id: id.to_string(),
+            score,
+            title: id.to_string(),
+            rating: None,
+        }
+    }
+
+    #[test]
+    fn fusion_boosts_items_that_rank_highly_in_multiple_lists() {
+        let content = Recommendations {
+            count: 2,
+            recommendations: vec![recommendation("a", 0.9), recommendation("b", 0.8)],
+        };
+        let collab = Recommendations {
+            count: 2,
+            recommendations: vec![recommendation("b", 0.9), recommendation("c", 0.8)],
+        };
+
+        let fused = fuse_recommendations(&[(content, 0.6), (collab, 0.4)], 60.0, 5);
+
+        assert_eq!(fused.recommendations[0].id, "b");
+    }
+}
@@ -0,0 +1,5 @@
+pub mod book;
+pub mod cache;
+pub mod collab;
+pub mod queue;
+pub mod recommend;
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
+
+use crate::book::Book;
+use crate::cache::EmbeddingCache;
+
+// crude chars-per-token estimate used to keep batches under the model's max sequence length
+const CHARS_PER_TOKEN: usize = 4;
+// the model's max sequence length, in tokens; longer descriptions are truncated before encoding
+const MAX_SEQUENCE_TOKENS: usize = 256;
+
+// accumulates pending books and reports when a batch is ready to flush, sized to stay under a
+// configurable max by both book count and approximate token length
+pub struct EmbeddingQueue {
+    max_batch_size: usize,
+    max_batch_tokens: usize,
+    pending: Vec<Book>,
+    pending_tokens: usize,
+}
+
+impl EmbeddingQueue {
+    pub fn new(max_batch_size: usize, max_batch_tokens: usize) -> Self {
+        Self {
+            max_batch_size,
+            max_batch_tokens,
+            pending: Vec::new(),
+            pending_tokens: 0,
+        }
+    }
+
+    // truncates book's description first so a single oversized document can't blow out a whole
+    // batch; returns the previously accumulated batch to flush if adding book would overflow it
+    pub fn enqueue(&mut self, mut book: Book) -> Option<Vec<Book>> {
+        truncate_description(&mut book);
+        let tokens = estimate_tokens(&book.description);
+
+        let would_overflow = !self.pending.is_empty()
+            && (self.pending.len() + 1 > self.max_batch_size
+                || self.pending_tokens + tokens > self.max_batch_tokens);
+
+        let ready = if would_overflow {
+            self.pending_tokens = 0;
+            Some(std::mem::take(&mut self.pending))
+        } else {
+            None
+        };
+
+        self.pending.push(book);
+        self.pending_tokens += tokens;
+        ready
+    }
+
+    // drain and return whatever remains queued, for the final flush once all books are enqueued
+    pub fn drain(&mut self) -> Vec<Book> {
+        self.pending_tokens = 0;
+        std::mem::take(&mut self.pending)
+    }
+}
+
+// truncates book's description to the max sequence length in place; exposed so callers can
+// truncate before hashing for the embedding cache, keeping the cache key in sync with the
+// description that actually gets encoded once the book reaches a batch
+pub fn truncate_description(book: &mut Book) {
+    let max_chars = MAX_SEQUENCE_TOKENS * CHARS_PER_TOKEN;
+    if book.description.len() <= max_chars {
+        return;
+    }
+
+    let mut cut = max_chars;
+    while !book.description.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    book.description.truncate(cut);
+}
+
+fn estimate_tokens(text: &str) -> usize {
+    (text.len() / CHARS_PER_TOKEN).max(1)
+}
+
+// encode every book in batch in one call to model.encode, cache the resulting embeddings
+// (unless no_cache is set), then write the batch's JSON.SET calls atomically via a pipeline
+pub fn flush_batch(
+    connection: &mut redis::Connection,
+    model: &SentenceEmbeddingsModel,
+    cache: &EmbeddingCache,
+    no_cache: bool,
+    mut batch: Vec<Book>,
+) -> Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    let descriptions: Vec<&str> = batch.iter().map(|b| b.description.as_str()).collect();
+    let embeddings = model.encode(&descriptions)?;
+
+    let mut pipeline = redis::pipe();
+    pipeline.atomic();
+    for (book, embedding) in batch.iter_mut().zip(embeddings) {
+        if !no_cache {
+            cache.put(connection, &book.description, &embedding)?;
+        }
+        book.embedding = Some(embedding);
+
+        let key = format!("book:{}", book.id);
+        pipeline
+            .cmd("JSON.SET")
+            .arg(key)
+            .arg("$")
+            .arg(serde_json::to_string(book)?);
+    }
+    pipeline.query::<()>(connection)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, description: &str) -> Book {
+        Book {
+            author: "author".to_string(),
+            id: id.to_string(),
+            description: description.to_string(),
+            embedding: None,
+            cf_embedding: None,
+            editions: Vec::new(),
+            genres: Vec::new(),
+            inventory: Vec::new(),
+            metrics: crate::book::Metrics {
+                rating_votes: 0,
+                score: 0.0,
+            },
+            pages: 0,
+            title: "title".to_string(),
+            url: "url".to_string(),
+            year_published: 2000,
+        }
+    }
+
+    #[test]
+    fn flushes_once_max_batch_size_is_exceeded() {
+        let mut queue = EmbeddingQueue::new(2, usize::MAX);
+
+        assert!(queue.enqueue(book("1", "a")).is_none());
+        assert!(queue.enqueue(book("2", "b")).is_none());
+        let flushed = queue.enqueue(book("3", "c")).expect("third book overflows batch size");
+
+        assert_eq!(flushed.len(), 2);
+        assert_eq!(queue.drain().len(), 1);
+    }
+
+    #[test]
+    fn truncates_descriptions_longer_than_the_max_sequence_length() {
+        let mut queue = EmbeddingQueue::new(usize::MAX, usize::MAX);
+        let long_description = "x".repeat(MAX_SEQUENCE_TOKENS * CHARS_PER_TOKEN + 100);
+
+        queue.enqueue(book("1", &long_description));
+        let pending = queue.drain();
+
+        assert_eq!(
+            pending[0].description.len(),
+            MAX_SEQUENCE_TOKENS * CHARS_PER_TOKEN
+        );
+    }
+}
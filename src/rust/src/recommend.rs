@@ -0,0 +1,318 @@
+use anyhow::Result;
+use byteorder::{LittleEndian, WriteBytesExt};
+use redis::{JsonCommands, Value};
+
+use crate::book::{Book, Recommendations};
+
+pub const INDEX_NAME: &str = "idx:books";
+
+// encode the embeddings as a byte array
+pub fn encode(fs: Vec<f32>) -> Vec<u8> {
+    let mut vec: Vec<u8> = Vec::new();
+    for f in fs {
+        vec.write_f32::<LittleEndian>(f).unwrap();
+    }
+    vec
+}
+
+// create the index if it doesn't exist
+pub fn create_index(connection: &mut redis::Connection) -> Result<()> {
+    let result: Result<Value, _> = redis::cmd("FT._LIST").query(connection);
+    if let Ok(Value::Bulk(ref values)) = result {
+        if values
+            .iter()
+            .any(|v| v == &Value::Status(String::from(INDEX_NAME)))
+        {
+            return Ok(());
+        }
+    }
+
+    redis::cmd("FT.CREATE")
+        .arg(INDEX_NAME)
+        .arg("ON")
+        .arg("JSON")
+        .arg("PREFIX")
+        .arg("1")
+        .arg("book:")
+        .arg("SCHEMA")
+        // author
+        .arg("$.author")
+        .arg("AS")
+        .arg("author")
+        .arg("TEXT")
+        // title
+        .arg("$.title")
+        .arg("AS")
+        .arg("title")
+        .arg("TEXT")
+        // description
+        .arg("$.description")
+        .arg("AS")
+        .arg("description")
+        .arg("TEXT")
+        // genres, for constraining hybrid recommendations to the same genre(s)
+        .arg("$.genres.*")
+        .arg("AS")
+        .arg("genres")
+        .arg("TAG")
+        // year published, for constraining hybrid recommendations to a publication range
+        .arg("$.year_published")
+        .arg("AS")
+        .arg("year_published")
+        .arg("NUMERIC")
+        // inventory status, for filtering to copies that are currently available
+        .arg("$.inventory[*].status")
+        .arg("AS")
+        .arg("status")
+        .arg("TAG")
+        // metrics.score, for filtering/blending on book rating
+        .arg("$.metrics.score")
+        .arg("AS")
+        .arg("rating")
+        .arg("NUMERIC")
+        // embedding
+        .arg("$.embedding")
+        .arg("AS")
+        .arg("embedding")
+        .arg("VECTOR")
+        // search parameters
+        .arg("HNSW")
+        .arg("6")
+        .arg("TYPE")
+        .arg("FLOAT32")
+        .arg("DIM")
+        .arg("384")
+        .arg("DISTANCE_METRIC")
+        .arg("COSINE")
+        .query::<()>(connection)?;
+    Ok(())
+}
+
+// run a KNN search directly against a vector, without needing an existing book id - this backs
+// both `get_recommendation` below and the HTTP service's free-text "find books like this" search
+pub fn get_recommendation_by_vector(
+    connection: &mut redis::Connection,
+    embedding: Vec<f32>,
+    k: u64,
+) -> Result<Recommendations> {
+    let encoded_embedding = encode(embedding);
+    let query = format!("*=>[KNN {} @embedding $vec AS score]", k);
+
+    let recommendations: Recommendations = redis::cmd("FT.SEARCH")
+        .arg(INDEX_NAME)
+        .arg(query)
+        .arg("PARAMS")
+        .arg(2)
+        .arg("vec")
+        .arg(encoded_embedding)
+        .arg("RETURN")
+        .arg("2")
+        .arg("title")
+        .arg("score")
+        .arg("SORTBY")
+        .arg("score")
+        .arg("LIMIT")
+        .arg(0)
+        .arg(k)
+        .arg("DIALECT")
+        .arg("2")
+        .query(connection)?;
+
+    Ok(recommendations)
+}
+
+// get the recommendations for a book
+pub fn get_recommendation(
+    connection: &mut redis::Connection,
+    key: &str,
+    k: u64,
+) -> Result<Recommendations> {
+    let book: Book = connection.json_get(key, "$")?;
+
+    if let Some(embedding) = book.embedding {
+        return get_recommendation_by_vector(connection, embedding, k);
+    }
+    anyhow::bail!("No embedding found for book {}", key);
+}
+
+// get the recommendations for a book by range
+pub fn get_recommendation_by_range(
+    connection: &mut redis::Connection,
+    key: &str,
+    radius: f32,
+    k: u64,
+) -> Result<Recommendations> {
+    let book: Book = connection.json_get(key, "$")?;
+
+    if let Some(embedding) = book.embedding {
+        let encoded_embedding = encode(embedding);
+        let query = "@embedding:[VECTOR_RANGE $radius $vec]=>{$YIELD_DISTANCE_AS: score}";
+
+        let recommendations: Recommendations = redis::cmd("FT.SEARCH")
+            .arg(INDEX_NAME)
+            .arg(query)
+            .arg("PARAMS")
+            .arg(4)
+            .arg("radius")
+            .arg(radius)
+            .arg("vec")
+            .arg(encoded_embedding)
+            .arg("RETURN")
+            .arg(2)
+            .arg("title")
+            .arg("score")
+            .arg("SORTBY")
+            .arg("score")
+            .arg("LIMIT")
+            .arg(0)
+            .arg(k)
+            .arg("DIALECT")
+            .arg("2")
+            .query(connection)?;
+
+        return Ok(recommendations);
+    }
+    anyhow::bail!("No embedding found for book {}", key);
+}
+
+// get recommendations constrained to a RediSearch pre-filter built from the seed book's own
+// metadata, then re-rank the KNN results by blending vector similarity with book rating
+#[allow(clippy::too_many_arguments)]
+pub fn get_hybrid_recommendation(
+    connection: &mut redis::Connection,
+    key: &str,
+    same_genre: bool,
+    min_rating: Option<f32>,
+    available_only: bool,
+    min_year: Option<u16>,
+    max_year: Option<u16>,
+    alpha: f32,
+    k: u64,
+) -> Result<Recommendations> {
+    let book: Book = connection.json_get(key, "$")?;
+
+    if let Some(embedding) = book.embedding.clone() {
+        let encoded_embedding = encode(embedding);
+        let filter = build_hybrid_filter(
+            &book,
+            same_genre,
+            min_rating,
+            available_only,
+            min_year,
+            max_year,
+        );
+        let query = format!("({})=>[KNN {} @embedding $vec AS score]", filter, k);
+
+        let mut recommendations: Recommendations = redis::cmd("FT.SEARCH")
+            .arg(INDEX_NAME)
+            .arg(query)
+            .arg("PARAMS")
+            .arg(2)
+            .arg("vec")
+            .arg(encoded_embedding)
+            .arg("RETURN")
+            .arg("3")
+            .arg("title")
+            .arg("score")
+            .arg("rating")
+            .arg("SORTBY")
+            .arg("score")
+            .arg("LIMIT")
+            .arg(0)
+            .arg(k)
+            .arg("DIALECT")
+            .arg("2")
+            .query(connection)?;
+
+        blend_with_rating(&mut recommendations, alpha);
+
+        return Ok(recommendations);
+    }
+    anyhow::bail!("No embedding found for book {}", key);
+}
+
+// assemble a RediSearch boolean pre-filter from the seed book's metadata, excluding the seed
+// book's own author so a book is never recommended alongside its own other editions
+fn build_hybrid_filter(
+    book: &Book,
+    same_genre: bool,
+    min_rating: Option<f32>,
+    available_only: bool,
+    min_year: Option<u16>,
+    max_year: Option<u16>,
+) -> String {
+    let mut clauses: Vec<String> = vec![format!("-@author:\"{}\"", escape_quotes(&book.author))];
+
+    if same_genre && !book.genres.is_empty() {
+        let genres = book
+            .genres
+            .iter()
+            .map(|g| escape_tag(g))
+            .collect::<Vec<_>>()
+            .join("|");
+        clauses.push(format!("@genres:{{{}}}", genres));
+    }
+
+    if let Some(min_rating) = min_rating {
+        clauses.push(format!("@rating:[{} +inf]", min_rating));
+    }
+
+    if available_only {
+        clauses.push("@status:{Available}".to_string());
+    }
+
+    if min_year.is_some() || max_year.is_some() {
+        let min_year = min_year.map_or("-inf".to_string(), |y| y.to_string());
+        let max_year = max_year.map_or("+inf".to_string(), |y| y.to_string());
+        clauses.push(format!("@year_published:[{} {}]", min_year, max_year));
+    }
+
+    clauses.join(" ")
+}
+
+// escape characters RediSearch treats as TAG field special characters: see
+// https://redis.io/docs/latest/develop/interact/search-and-query/advanced-concepts/escaping/
+fn escape_tag(value: &str) -> String {
+    const SPECIAL: &[char] = &[
+        ',', '.', '<', '>', '{', '}', '[', ']', '"', '\'', ':', ';', '!', '@', '#', '$', '%', '^',
+        '&', '*', '(', ')', '-', '+', '=', '~', '|', ' ', '/', '\\',
+    ];
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if SPECIAL.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// escape double quotes so an author name can't break out of the quoted TEXT clause
+fn escape_quotes(value: &str) -> String {
+    value.replace('"', "\\\"")
+}
+
+// re-rank recommendations by blending vector similarity with the book rating, normalized by
+// dividing by the highest rating among the results (0 when no result has a positive rating):
+// final = alpha * (1 - cosine_distance) + (1 - alpha) * normalized_rating
+fn blend_with_rating(recommendations: &mut Recommendations, alpha: f32) {
+    let max_rating = recommendations
+        .recommendations
+        .iter()
+        .fold(0.0_f32, |max, r| max.max(r.rating.unwrap_or(0.0)));
+
+    for r in &mut recommendations.recommendations {
+        let normalized_rating = if max_rating > 0.0 {
+            r.rating.unwrap_or(0.0) / max_rating
+        } else {
+            0.0
+        };
+        r.score = alpha * (1.0 - r.score) + (1.0 - alpha) * normalized_rating;
+    }
+
+    recommendations.recommendations.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
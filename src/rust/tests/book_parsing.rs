@@ -0,0 +1,80 @@
+mod common;
+
+use redis::{FromRedisValue, Value};
+use ru402::book::{Book, Recommendations};
+
+#[test]
+fn book_parses_a_well_formed_json_get_reply() {
+    let json = r#"[{
+        "author": "author",
+        "id": "1",
+        "description": "a book",
+        "embedding": null,
+        "editions": [],
+        "genres": [],
+        "inventory": [],
+        "metrics": {"rating_votes": 0, "score": 0.0},
+        "pages": 100,
+        "title": "title",
+        "url": "url",
+        "year_published": 2000
+    }]"#;
+
+    let book = Book::from_redis_value(&common::data(json)).unwrap();
+    assert_eq!(book.id, "1");
+}
+
+#[test]
+fn book_errors_instead_of_panicking_on_an_empty_json_array() {
+    let result = Book::from_redis_value(&common::data("[]"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn recommendations_errors_instead_of_panicking_on_a_non_array_reply() {
+    let result = Recommendations::from_redis_value(&Value::Status("OK".to_string()));
+    assert!(result.is_err());
+}
+
+#[test]
+fn recommendations_errors_instead_of_panicking_on_an_empty_reply() {
+    let result = Recommendations::from_redis_value(&common::bulk(vec![]));
+    assert!(result.is_err());
+}
+
+#[test]
+fn recommendations_handles_a_count_with_zero_documents() {
+    let reply = common::ft_search_reply(0, &[]);
+    let recommendations = Recommendations::from_redis_value(&reply).unwrap();
+
+    assert_eq!(recommendations.count, 0);
+    assert!(recommendations.recommendations.is_empty());
+}
+
+#[test]
+fn recommendations_errors_instead_of_panicking_on_a_truncated_document_pair() {
+    // a dangling id with no fields array following it
+    let reply = common::bulk(vec![common::int(1), common::data("book:1")]);
+    let result = Recommendations::from_redis_value(&reply);
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn recommendations_errors_instead_of_panicking_when_document_fields_are_not_an_array() {
+    let reply = common::ft_search_reply(1, &[("book:1", common::data("not an array"))]);
+    let result = Recommendations::from_redis_value(&reply);
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn recommendations_defaults_missing_title_and_score_fields() {
+    let reply = common::ft_search_reply(1, &[("book:1", common::doc_fields(&[("rating", "4.5")]))]);
+    let recommendations = Recommendations::from_redis_value(&reply).unwrap();
+
+    let recommendation = &recommendations.recommendations[0];
+    assert_eq!(recommendation.title, "");
+    assert_eq!(recommendation.score, 0.0);
+    assert_eq!(recommendation.rating, Some(4.5));
+}
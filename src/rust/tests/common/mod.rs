@@ -0,0 +1,33 @@
+use redis::Value;
+
+pub fn bulk(items: Vec<Value>) -> Value {
+    Value::Bulk(items)
+}
+
+pub fn data(s: &str) -> Value {
+    Value::Data(s.as_bytes().to_vec())
+}
+
+pub fn int(i: i64) -> Value {
+    Value::Int(i)
+}
+
+// a document's RESP field list as FT.SEARCH returns it: [field, value, field, value, ...]
+pub fn doc_fields(fields: &[(&str, &str)]) -> Value {
+    let mut values = Vec::with_capacity(fields.len() * 2);
+    for (field, value) in fields {
+        values.push(data(field));
+        values.push(data(value));
+    }
+    bulk(values)
+}
+
+// a full FT.SEARCH reply: a leading count, followed by id, fields pairs per document
+pub fn ft_search_reply(count: i64, docs: &[(&str, Value)]) -> Value {
+    let mut values = vec![int(count)];
+    for (id, fields) in docs {
+        values.push(data(id));
+        values.push(fields.clone());
+    }
+    bulk(values)
+}